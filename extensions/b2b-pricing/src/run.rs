@@ -2,36 +2,492 @@ use super::schema;
 use shopify_function::prelude::*;
 use shopify_function::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
 #[derive(Deserialize, Serialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Configuration {
+    /// Parsed leniently per-rule: a single malformed admin-entered rule is
+    /// dropped rather than failing `Vec<PricingRule>` deserialization for
+    /// the whole array, which would silently disable every rule for every
+    /// customer.
+    #[serde(deserialize_with = "deserialize_pricing_rules_leniently")]
     pub pricing_rules: Vec<PricingRule>,
+    /// How to resolve multiple matching rules: the highest-priority rule
+    /// wins, or whichever rule gives the customer the lowest final price.
+    #[serde(default)]
+    pub resolution: Resolution,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Resolution {
+    #[default]
+    PriorityFirst,
+    BestForCustomer,
 }
 
 #[derive(Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PricingRule {
     pub id: String,
+    #[serde(default)]
     pub customer_tags: Vec<String>,
+    #[serde(default)]
     pub product_ids: Vec<String>,
+    #[serde(default)]
     pub collection_ids: Vec<String>,
     pub discount_type: String, // "percentage" or "fixed"
+    /// Admin-entered metafield JSON frequently stringifies numbers; accept
+    /// either form.
+    #[serde(deserialize_with = "deserialize_number_from_string_or_number")]
     pub discount_value: f64,
+    #[serde(deserialize_with = "deserialize_i32_from_string_or_number")]
     pub priority: i32,
+    /// Admin-entered metafield JSON frequently stringifies booleans; accept
+    /// either form.
+    #[serde(deserialize_with = "deserialize_bool_from_string_or_bool")]
     pub is_active: bool,
+    /// Quantity-break tiers, evaluated against the summed quantity of the
+    /// lines this rule targets. The highest qualifying tier wins; falls
+    /// back to `discount_type`/`discount_value` when none qualify.
+    #[serde(default)]
+    pub tiers: Vec<QuantityTier>,
+    /// Fixed-discount amount in minor units (e.g. cents), paired with
+    /// `currency_code`. Takes precedence over `discount_value` for
+    /// `discount_type: "fixed"` rules; ignored for percentages.
+    #[serde(default)]
+    pub amount_minor: Option<i64>,
+    /// ISO-4217 currency the `amount_minor` fixed discount is denominated
+    /// in. A fixed rule is skipped when this doesn't match the cart's
+    /// presentment currency, rather than applying a wrong-currency amount.
+    #[serde(default)]
+    pub currency_code: Option<String>,
+    /// Whether `customer_tags` requires any one tag or all of them.
+    #[serde(default)]
+    pub match_mode: MatchMode,
+    /// A buyer carrying any of these tags disqualifies the rule entirely,
+    /// regardless of `match_mode`.
+    #[serde(default)]
+    pub excluded_customer_tags: Vec<String>,
+    /// Products removed from targeting even when collection-matched.
+    #[serde(default)]
+    pub excluded_product_ids: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchMode {
+    #[default]
+    AnyTag,
+    AllTags,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QuantityTier {
+    #[serde(deserialize_with = "deserialize_i32_from_string_or_number")]
+    pub min_quantity: i32,
+    pub discount_type: String,
+    /// Admin-entered metafield JSON frequently stringifies numbers; accept
+    /// either form.
+    #[serde(deserialize_with = "deserialize_number_from_string_or_number")]
+    pub discount_value: f64,
+    /// Same minor-units/currency handling as `PricingRule::amount_minor`,
+    /// since a tier can independently be `discount_type: "fixed"`.
+    #[serde(default)]
+    pub amount_minor: Option<i64>,
+    #[serde(default)]
+    pub currency_code: Option<String>,
+}
+
+impl QuantityTier {
+    fn to_value(&self) -> schema::Value {
+        match self.discount_type.as_str() {
+            "fixed" => schema::Value::FixedAmount(schema::FixedAmount {
+                amount: self.amount_minor.map(minor_to_major).unwrap_or(self.discount_value),
+            }),
+            _ => schema::Value::Percentage(schema::Percentage {
+                value: self.discount_value,
+            }),
+        }
+    }
+
+    /// See `fixed_discount_is_viable_for_currency`.
+    fn is_viable_for_currency(&self, cart_currency_code: &str) -> bool {
+        fixed_discount_is_viable_for_currency(
+            &self.discount_type,
+            self.amount_minor,
+            &self.currency_code,
+            cart_currency_code,
+        )
+    }
+}
+
+impl Configuration {
+    fn from_metafield(input: &schema::run::Input) -> Option<Self> {
+        let json_value = input.discount_node.metafield.as_ref()?.json_value.clone();
+        serde_json::from_value(json_value).ok()
+    }
+}
+
+impl PricingRule {
+    /// A rule matches a buyer when it's active, the buyer carries none of
+    /// `excluded_customer_tags`, and `customer_tags` is satisfied per
+    /// `match_mode` (any one tag, or all of them).
+    fn matches_customer(&self, customer_tags: &HashSet<String>) -> bool {
+        if !self.is_active {
+            return false;
+        }
+
+        if self
+            .excluded_customer_tags
+            .iter()
+            .any(|tag| customer_tags.contains(&tag.to_lowercase()))
+        {
+            return false;
+        }
+
+        match self.match_mode {
+            MatchMode::AnyTag => self
+                .customer_tags
+                .iter()
+                .any(|tag| customer_tags.contains(&tag.to_lowercase())),
+            MatchMode::AllTags => {
+                !self.customer_tags.is_empty()
+                    && self
+                        .customer_tags
+                        .iter()
+                        .all(|tag| customer_tags.contains(&tag.to_lowercase()))
+            }
+        }
+    }
+
+    /// Whether this rule targets a given cart line: excluded products are
+    /// never targeted; otherwise by product id, by collection membership,
+    /// or every (non-excluded) line when neither list is set.
+    fn targets_line(&self, line: &schema::CartLine) -> bool {
+        let product = &line.merchandise.product;
+
+        if self.excluded_product_ids.contains(&product.id) {
+            return false;
+        }
+
+        if self.product_ids.is_empty() && self.collection_ids.is_empty() {
+            return true;
+        }
+
+        if self.product_ids.contains(&product.id) {
+            return true;
+        }
+
+        product
+            .in_any_collection
+            .iter()
+            .any(|membership| membership.is_member && self.collection_ids.contains(&membership.collection_id))
+    }
+
+    /// The highest quantity tier whose `min_quantity` is met by `quantity`,
+    /// restricted to tiers that are safe to apply in the cart's currency.
+    fn tier_for_quantity(&self, quantity: i32, cart_currency_code: &str) -> Option<&QuantityTier> {
+        self.tiers
+            .iter()
+            .filter(|tier| tier.min_quantity <= quantity)
+            .filter(|tier| tier.is_viable_for_currency(cart_currency_code))
+            .max_by_key(|tier| tier.min_quantity)
+    }
+
+    /// The discount to apply for a given summed line quantity: the richest
+    /// currency-safe qualifying tier, or the rule's base discount when none
+    /// qualify.
+    fn value_for_quantity(&self, quantity: i32, cart_currency_code: &str) -> schema::Value {
+        match self.tier_for_quantity(quantity, cart_currency_code) {
+            Some(tier) => tier.to_value(),
+            None => self.base_value(),
+        }
+    }
+
+    /// The rule's base (non-tiered) discount. Fixed discounts are computed
+    /// in integer minor units and converted to major units once, which
+    /// keeps them free of floating-point rounding error. `is_viable_for_currency`
+    /// already rejects fixed rules without `amount_minor` before this is
+    /// ever called, so the `discount_value` fallback below is unreachable
+    /// for fixed rules in practice.
+    fn base_value(&self) -> schema::Value {
+        match self.discount_type.as_str() {
+            "fixed" => schema::Value::FixedAmount(schema::FixedAmount {
+                amount: self
+                    .amount_minor
+                    .map(minor_to_major)
+                    .unwrap_or(self.discount_value),
+            }),
+            _ => schema::Value::Percentage(schema::Percentage {
+                value: self.discount_value,
+            }),
+        }
+    }
+
+    /// See `fixed_discount_is_viable_for_currency`.
+    fn is_viable_for_currency(&self, cart_currency_code: &str) -> bool {
+        fixed_discount_is_viable_for_currency(
+            &self.discount_type,
+            self.amount_minor,
+            &self.currency_code,
+            cart_currency_code,
+        )
+    }
+}
+
+/// Fixed discounts (rule- or tier-level) without `amount_minor`, or
+/// denominated in a currency that doesn't match the cart's presentment
+/// currency, can't be applied safely and are skipped entirely; percentage
+/// discounts are always viable.
+fn fixed_discount_is_viable_for_currency(
+    discount_type: &str,
+    amount_minor: Option<i64>,
+    currency_code: &Option<String>,
+    cart_currency_code: &str,
+) -> bool {
+    if discount_type != "fixed" {
+        return true;
+    }
+
+    if amount_minor.is_none() {
+        return false;
+    }
+
+    match currency_code {
+        Some(currency_code) => currency_code.eq_ignore_ascii_case(cart_currency_code),
+        None => true,
+    }
+}
+
+fn minor_to_major(amount_minor: i64) -> f64 {
+    amount_minor as f64 / 100.0
+}
+
+/// Accepts a JSON number or a numeric string, e.g. `10` or `"10"`.
+fn deserialize_number_from_string_or_number<'de, D>(deserializer: D) -> std::result::Result<f64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString {
+        Number(f64),
+        Text(String),
+    }
+
+    match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Number(number) => Ok(number),
+        NumberOrString::Text(text) => text.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+/// Accepts a JSON integer, a whole-number JSON float (admin tooling and
+/// spreadsheet exports frequently emit `1.0` instead of `1`), or an integer
+/// string, e.g. `1`, `1.0`, or `"1"`.
+fn deserialize_i32_from_string_or_number<'de, D>(deserializer: D) -> std::result::Result<i32, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString {
+        Number(f64),
+        Text(String),
+    }
+
+    match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Number(number) => Ok(number as i32),
+        NumberOrString::Text(text) => text.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+/// Accepts a JSON boolean, numeric `0`/`1` (admin tooling sometimes emits
+/// booleans as numbers), or their string form, e.g. `true`, `1`, or
+/// `"true"`.
+fn deserialize_bool_from_string_or_bool<'de, D>(deserializer: D) -> std::result::Result<bool, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum BoolOrNumberOrString {
+        Bool(bool),
+        Number(f64),
+        Text(String),
+    }
+
+    match BoolOrNumberOrString::deserialize(deserializer)? {
+        BoolOrNumberOrString::Bool(value) => Ok(value),
+        BoolOrNumberOrString::Number(number) => Ok(number != 0.0),
+        BoolOrNumberOrString::Text(text) => match text.to_lowercase().as_str() {
+            "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            other => Err(serde::de::Error::custom(format!("invalid boolean string: {other}"))),
+        },
+    }
+}
+
+/// Parses each rule independently so one malformed admin-entered rule
+/// doesn't fail `Vec<PricingRule>` deserialization for the entire
+/// configuration; a rule that fails to parse is dropped rather than
+/// disabling every rule for every customer.
+fn deserialize_pricing_rules_leniently<'de, D>(deserializer: D) -> std::result::Result<Vec<PricingRule>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let rules = Vec::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(rules.into_iter().filter_map(|rule| serde_json::from_value(rule).ok()).collect())
+}
+
+fn customer_tags(input: &schema::run::Input) -> HashSet<String> {
+    input
+        .cart
+        .buyer_identity
+        .as_ref()
+        .and_then(|identity| identity.customer.as_ref())
+        .map(|customer| customer.tags.iter().map(|tag| tag.to_lowercase()).collect())
+        .unwrap_or_default()
+}
+
+/// Discounts from only the highest-priority matching rule, applied to the
+/// lines it targets. This is the original, default resolution.
+fn priority_first_discounts(
+    matching_rules: &[&PricingRule],
+    lines: &[schema::CartLine],
+    cart_currency_code: &str,
+) -> Vec<schema::Discount> {
+    let mut sorted_rules = matching_rules.to_vec();
+    sorted_rules.sort_by(|a, b| b.priority.cmp(&a.priority));
+
+    let Some(rule) = sorted_rules.first() else {
+        return vec![];
+    };
+
+    let targeted_lines: Vec<&schema::CartLine> = lines.iter().filter(|line| rule.targets_line(line)).collect();
+    if targeted_lines.is_empty() {
+        return vec![];
+    }
+
+    let total_quantity: i32 = targeted_lines.iter().map(|line| line.quantity as i32).sum();
+    let value = rule.value_for_quantity(total_quantity, cart_currency_code);
+
+    targeted_lines
+        .iter()
+        .map(|line| schema::Discount {
+            targets: vec![schema::Target::CartLine(schema::CartLineTarget {
+                id: line.id.clone(),
+                quantity: None,
+            })],
+            value: value.clone(),
+        })
+        .collect()
+}
+
+/// Per line, evaluates every matching rule that targets it and keeps
+/// whichever yields the lowest final price, using integer-minor-unit money
+/// math to avoid float rounding error.
+fn best_for_customer_discounts(
+    matching_rules: &[&PricingRule],
+    lines: &[schema::CartLine],
+    cart_currency_code: &str,
+) -> Vec<schema::Discount> {
+    let rule_values: Vec<(&PricingRule, schema::Value)> = matching_rules
+        .iter()
+        .filter_map(|rule| {
+            let targeted_lines: Vec<&schema::CartLine> = lines.iter().filter(|line| rule.targets_line(line)).collect();
+            if targeted_lines.is_empty() {
+                return None;
+            }
+            let total_quantity: i32 = targeted_lines.iter().map(|line| line.quantity as i32).sum();
+            Some((*rule, rule.value_for_quantity(total_quantity, cart_currency_code)))
+        })
+        .collect();
+
+    lines
+        .iter()
+        .filter_map(|line| {
+            let original_amount_minor = line_amount_minor(line);
+            rule_values
+                .iter()
+                .filter(|(rule, _)| rule.targets_line(line))
+                .map(|(_, value)| value.clone())
+                .min_by_key(|value| final_amount_minor(value, original_amount_minor))
+                .map(|value| schema::Discount {
+                    targets: vec![schema::Target::CartLine(schema::CartLineTarget {
+                        id: line.id.clone(),
+                        quantity: None,
+                    })],
+                    value,
+                })
+        })
+        .collect()
+}
+
+fn line_amount_minor(line: &schema::CartLine) -> i64 {
+    major_to_minor(line.cost.total_amount.amount.parse().unwrap_or(0.0))
+}
+
+fn major_to_minor(amount_major: f64) -> i64 {
+    (amount_major * 100.0).round() as i64
+}
+
+/// The line's price after applying `value`, in minor units. Clamped to
+/// zero so an admin-entered percentage over 100 can't produce a negative
+/// "final price" that would always win the `bestForCustomer` comparison
+/// over every legitimate discount.
+fn final_amount_minor(value: &schema::Value, original_amount_minor: i64) -> i64 {
+    match value {
+        schema::Value::Percentage(percentage) => {
+            let retained_fraction = (100.0 - percentage.value) / 100.0;
+            (((original_amount_minor as f64) * retained_fraction).round() as i64).max(0)
+        }
+        schema::Value::FixedAmount(fixed_amount) => {
+            (original_amount_minor - major_to_minor(fixed_amount.amount)).max(0)
+        }
+    }
 }
 
 #[shopify_function]
-fn run(_input: schema::run::Input) -> Result<schema::FunctionRunResult> {
-    // For now, return no discount to get the function compiling
-    // We'll implement the full logic once we understand the generated schema better
+fn run(input: schema::run::Input) -> Result<schema::FunctionRunResult> {
     let no_discount = schema::FunctionRunResult {
         discounts: vec![],
         discount_application_strategy: schema::DiscountApplicationStrategy::First,
     };
 
-    Ok(no_discount)
+    let Some(config) = Configuration::from_metafield(&input) else {
+        return Ok(no_discount);
+    };
+
+    let customer_tags = customer_tags(&input);
+    let cart_currency_code = &input.cart.cost.total_amount.currency_code;
+
+    let matching_rules: Vec<&PricingRule> = config
+        .pricing_rules
+        .iter()
+        .filter(|rule| rule.matches_customer(&customer_tags))
+        .filter(|rule| rule.is_viable_for_currency(cart_currency_code))
+        .collect();
+
+    if matching_rules.is_empty() {
+        return Ok(no_discount);
+    }
+
+    let discounts = match config.resolution {
+        Resolution::PriorityFirst => priority_first_discounts(&matching_rules, &input.cart.lines, cart_currency_code),
+        Resolution::BestForCustomer => best_for_customer_discounts(&matching_rules, &input.cart.lines, cart_currency_code),
+    };
+
+    if discounts.is_empty() {
+        return Ok(no_discount);
+    }
+
+    Ok(schema::FunctionRunResult {
+        discounts,
+        discount_application_strategy: schema::DiscountApplicationStrategy::First,
+    })
 }
 
 #[cfg(test)]
@@ -49,6 +505,12 @@ mod tests {
                         "metafield": null
                     },
                     "cart": {
+                        "cost": {
+                            "totalAmount": {
+                                "amount": "0.00",
+                                "currencyCode": "USD"
+                            }
+                        },
                         "buyerIdentity": {
                             "customer": {
                                 "tags": []
@@ -80,7 +542,14 @@ mod tests {
                 discount_value: 10.0,
                 priority: 1,
                 is_active: true,
+                tiers: vec![],
+                amount_minor: None,
+                currency_code: None,
+                match_mode: MatchMode::AnyTag,
+                excluded_customer_tags: vec![],
+                excluded_product_ids: vec![],
             }],
+            resolution: Resolution::PriorityFirst,
         };
 
         let result = run_function_with_input(
@@ -94,6 +563,12 @@ mod tests {
                         }}
                     }},
                     "cart": {{
+                        "cost": {{
+                            "totalAmount": {{
+                                "amount": "0.00",
+                                "currencyCode": "USD"
+                            }}
+                        }},
                         "buyerIdentity": {{
                             "customer": {{
                                 "tags": []
@@ -128,7 +603,14 @@ mod tests {
                 discount_value: 10.0,
                 priority: 1,
                 is_active: true,
+                tiers: vec![],
+                amount_minor: None,
+                currency_code: None,
+                match_mode: MatchMode::AnyTag,
+                excluded_customer_tags: vec![],
+                excluded_product_ids: vec![],
             }],
+            resolution: Resolution::PriorityFirst,
         };
 
         let result = run_function_with_input(
@@ -142,6 +624,12 @@ mod tests {
                         }}
                     }},
                     "cart": {{
+                        "cost": {{
+                            "totalAmount": {{
+                                "amount": "0.00",
+                                "currencyCode": "USD"
+                            }}
+                        }},
                         "buyerIdentity": {{
                             "customer": {{
                                 "tags": ["wholesale"]
@@ -151,6 +639,12 @@ mod tests {
                             {{
                                 "id": "gid://shopify/CartLine/1",
                                 "quantity": 1,
+                                "cost": {{
+                                    "totalAmount": {{
+                                        "amount": "100.00",
+                                        "currencyCode": "USD"
+                                    }}
+                                }},
                                 "merchandise": {{
                                     "__typename": "ProductVariant",
                                     "id": "gid://shopify/ProductVariant/1",
@@ -169,9 +663,784 @@ mod tests {
             ),
         )?;
 
-        // Currently returns no discount - this will change when we implement the full logic
-        assert!(result.discounts.is_empty());
+        assert_eq!(result.discounts.len(), 1);
+        assert_eq!(
+            result.discounts[0].targets,
+            vec![schema::Target::CartLine(schema::CartLineTarget {
+                id: "gid://shopify/CartLine/1".to_string(),
+                quantity: None,
+            })]
+        );
+        assert_eq!(
+            result.discounts[0].value,
+            schema::Value::Percentage(schema::Percentage { value: 10.0 })
+        );
         assert_eq!(result.discount_application_strategy, schema::DiscountApplicationStrategy::First);
         Ok(())
     }
+
+    fn tiered_rule() -> PricingRule {
+        PricingRule {
+            id: "tiered-rule".to_string(),
+            customer_tags: vec!["wholesale".to_string()],
+            product_ids: vec![],
+            collection_ids: vec![],
+            discount_type: "percentage".to_string(),
+            discount_value: 5.0,
+            priority: 1,
+            is_active: true,
+            tiers: vec![
+                QuantityTier {
+                    min_quantity: 10,
+                    discount_type: "percentage".to_string(),
+                    discount_value: 15.0,
+                    amount_minor: None,
+                    currency_code: None,
+                },
+                QuantityTier {
+                    min_quantity: 50,
+                    discount_type: "percentage".to_string(),
+                    discount_value: 25.0,
+                    amount_minor: None,
+                    currency_code: None,
+                },
+            ],
+            amount_minor: None,
+            currency_code: None,
+            match_mode: MatchMode::AnyTag,
+            excluded_customer_tags: vec![],
+            excluded_product_ids: vec![],
+        }
+    }
+
+    fn cart_with_quantity(quantity: i32) -> String {
+        format!(
+            r#"
+                {{
+                    "id": "gid://shopify/CartLine/1",
+                    "quantity": {quantity},
+                    "cost": {{
+                        "totalAmount": {{
+                            "amount": "100.00",
+                            "currencyCode": "USD"
+                        }}
+                    }},
+                    "merchandise": {{
+                        "__typename": "ProductVariant",
+                        "id": "gid://shopify/ProductVariant/1",
+                        "product": {{
+                            "id": "gid://shopify/Product/1",
+                            "title": "Test Product",
+                            "inAnyCollection": []
+                        }}
+                    }}
+                }}
+            "#
+        )
+    }
+
+    fn run_with_single_line(config: &Configuration, quantity: i32) -> Result<schema::FunctionRunResult> {
+        run_with_single_line_and_currency(config, quantity, "USD")
+    }
+
+    fn run_with_single_line_and_currency(
+        config: &Configuration,
+        quantity: i32,
+        cart_currency_code: &str,
+    ) -> Result<schema::FunctionRunResult> {
+        run_function_with_input(
+            run,
+            &format!(
+                r#"
+                {{
+                    "discountNode": {{
+                        "metafield": {{
+                            "jsonValue": {}
+                        }}
+                    }},
+                    "cart": {{
+                        "cost": {{
+                            "totalAmount": {{
+                                "amount": "0.00",
+                                "currencyCode": "{}"
+                            }}
+                        }},
+                        "buyerIdentity": {{
+                            "customer": {{
+                                "tags": ["wholesale"]
+                            }}
+                        }},
+                        "lines": [{}]
+                    }}
+                }}
+                "#,
+                serde_json::to_string(config).unwrap(),
+                cart_currency_code,
+                cart_with_quantity(quantity)
+            ),
+        )
+    }
+
+    #[test]
+    fn test_below_tier_threshold_uses_base_discount() -> Result<()> {
+        let config = Configuration {
+            pricing_rules: vec![tiered_rule()],
+            resolution: Resolution::PriorityFirst,
+        };
+
+        let result = run_with_single_line(&config, 5)?;
+
+        assert_eq!(
+            result.discounts[0].value,
+            schema::Value::Percentage(schema::Percentage { value: 5.0 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_exact_tier_threshold_applies_tier() -> Result<()> {
+        let config = Configuration {
+            pricing_rules: vec![tiered_rule()],
+            resolution: Resolution::PriorityFirst,
+        };
+
+        let result = run_with_single_line(&config, 10)?;
+
+        assert_eq!(
+            result.discounts[0].value,
+            schema::Value::Percentage(schema::Percentage { value: 15.0 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_tier_selects_richest_qualifying_tier() -> Result<()> {
+        let config = Configuration {
+            pricing_rules: vec![tiered_rule()],
+            resolution: Resolution::PriorityFirst,
+        };
+
+        let result = run_with_single_line(&config, 75)?;
+
+        assert_eq!(
+            result.discounts[0].value,
+            schema::Value::Percentage(schema::Percentage { value: 25.0 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrong_currency_fixed_tier_is_skipped_for_next_best_tier() -> Result<()> {
+        let mut rule = tiered_rule();
+        rule.tiers.push(QuantityTier {
+            min_quantity: 60,
+            discount_type: "fixed".to_string(),
+            discount_value: 0.0,
+            amount_minor: Some(500),
+            currency_code: Some("EUR".to_string()),
+        });
+        let config = Configuration {
+            pricing_rules: vec![rule],
+            resolution: Resolution::PriorityFirst,
+        };
+
+        // Cart is USD; the EUR-denominated fixed tier would otherwise be
+        // the richest qualifying tier at quantity 75 (min_quantity 60 beats
+        // 50), but must be skipped, falling back to the 50-unit 25% tier
+        // instead of a wrong-currency fixed amount.
+        let result = run_with_single_line(&config, 75)?;
+
+        assert_eq!(
+            result.discounts[0].value,
+            schema::Value::Percentage(schema::Percentage { value: 25.0 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tier_whole_number_float_fields_still_apply() -> Result<()> {
+        // Same admin-tooling quirk as `PricingRule`'s numeric fields, but
+        // for a tier's `minQuantity`/`discountValue` entered as `10.0`.
+        let config_json = r#"
+            {
+                "pricingRules": [
+                    {
+                        "id": "tiered-rule",
+                        "customerTags": ["wholesale"],
+                        "discountType": "percentage",
+                        "discountValue": 5.0,
+                        "priority": 1,
+                        "isActive": true,
+                        "tiers": [
+                            {
+                                "minQuantity": 10.0,
+                                "discountType": "percentage",
+                                "discountValue": 15.0
+                            }
+                        ]
+                    }
+                ]
+            }
+        "#;
+
+        let result = run_function_with_input(
+            run,
+            &format!(
+                r#"
+                {{
+                    "discountNode": {{
+                        "metafield": {{
+                            "jsonValue": {config_json}
+                        }}
+                    }},
+                    "cart": {{
+                        "cost": {{
+                            "totalAmount": {{
+                                "amount": "0.00",
+                                "currencyCode": "USD"
+                            }}
+                        }},
+                        "buyerIdentity": {{
+                            "customer": {{
+                                "tags": ["wholesale"]
+                            }}
+                        }},
+                        "lines": [{}]
+                    }}
+                }}
+                "#,
+                cart_with_quantity(10)
+            ),
+        )?;
+
+        assert_eq!(result.discounts.len(), 1);
+        assert_eq!(
+            result.discounts[0].value,
+            schema::Value::Percentage(schema::Percentage { value: 15.0 })
+        );
+        Ok(())
+    }
+
+    fn fixed_rule(currency_code: &str) -> PricingRule {
+        PricingRule {
+            id: "fixed-rule".to_string(),
+            customer_tags: vec!["wholesale".to_string()],
+            product_ids: vec![],
+            collection_ids: vec![],
+            discount_type: "fixed".to_string(),
+            discount_value: 0.0,
+            priority: 1,
+            is_active: true,
+            tiers: vec![],
+            amount_minor: Some(500),
+            currency_code: Some(currency_code.to_string()),
+            match_mode: MatchMode::AnyTag,
+            excluded_customer_tags: vec![],
+            excluded_product_ids: vec![],
+        }
+    }
+
+    #[test]
+    fn test_fixed_discount_uses_minor_units() -> Result<()> {
+        let config = Configuration {
+            pricing_rules: vec![fixed_rule("USD")],
+            resolution: Resolution::PriorityFirst,
+        };
+
+        let result = run_with_single_line_and_currency(&config, 1, "USD")?;
+
+        assert_eq!(
+            result.discounts[0].value,
+            schema::Value::FixedAmount(schema::FixedAmount { amount: 5.0 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fixed_discount_skipped_on_currency_mismatch() -> Result<()> {
+        let config = Configuration {
+            pricing_rules: vec![fixed_rule("USD")],
+            resolution: Resolution::PriorityFirst,
+        };
+
+        let result = run_with_single_line_and_currency(&config, 1, "EUR")?;
+
+        assert!(result.discounts.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stringified_metafield_fields_still_apply() -> Result<()> {
+        let config_json = r#"
+            {
+                "pricingRules": [
+                    {
+                        "id": "admin-entered-rule",
+                        "customerTags": ["wholesale"],
+                        "discountType": "percentage",
+                        "discountValue": "10",
+                        "priority": "1",
+                        "isActive": "true"
+                    }
+                ]
+            }
+        "#;
+
+        let result = run_function_with_input(
+            run,
+            &format!(
+                r#"
+                {{
+                    "discountNode": {{
+                        "metafield": {{
+                            "jsonValue": {config_json}
+                        }}
+                    }},
+                    "cart": {{
+                        "cost": {{
+                            "totalAmount": {{
+                                "amount": "0.00",
+                                "currencyCode": "USD"
+                            }}
+                        }},
+                        "buyerIdentity": {{
+                            "customer": {{
+                                "tags": ["wholesale"]
+                            }}
+                        }},
+                        "lines": [{}]
+                    }}
+                }}
+                "#,
+                cart_with_quantity(1)
+            ),
+        )?;
+
+        assert_eq!(result.discounts.len(), 1);
+        assert_eq!(
+            result.discounts[0].value,
+            schema::Value::Percentage(schema::Percentage { value: 10.0 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_whole_number_float_and_numeric_bool_fields_still_apply() -> Result<()> {
+        // Admin tooling/spreadsheet exports frequently emit native JSON
+        // types like `1.0` for an integer and `1`/`0` for a boolean, rather
+        // than stringifying them.
+        let config_json = r#"
+            {
+                "pricingRules": [
+                    {
+                        "id": "admin-entered-rule",
+                        "customerTags": ["wholesale"],
+                        "discountType": "percentage",
+                        "discountValue": 10.0,
+                        "priority": 1.0,
+                        "isActive": 1
+                    }
+                ]
+            }
+        "#;
+
+        let result = run_function_with_input(
+            run,
+            &format!(
+                r#"
+                {{
+                    "discountNode": {{
+                        "metafield": {{
+                            "jsonValue": {config_json}
+                        }}
+                    }},
+                    "cart": {{
+                        "cost": {{
+                            "totalAmount": {{
+                                "amount": "0.00",
+                                "currencyCode": "USD"
+                            }}
+                        }},
+                        "buyerIdentity": {{
+                            "customer": {{
+                                "tags": ["wholesale"]
+                            }}
+                        }},
+                        "lines": [{}]
+                    }}
+                }}
+                "#,
+                cart_with_quantity(1)
+            ),
+        )?;
+
+        assert_eq!(result.discounts.len(), 1);
+        assert_eq!(
+            result.discounts[0].value,
+            schema::Value::Percentage(schema::Percentage { value: 10.0 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_rule_is_dropped_without_disabling_other_rules() -> Result<()> {
+        // The first rule is missing `discountValue` and can't be parsed;
+        // it must not take the well-formed second rule down with it.
+        let config_json = r#"
+            {
+                "pricingRules": [
+                    {
+                        "id": "malformed-rule",
+                        "customerTags": ["wholesale"],
+                        "discountType": "percentage",
+                        "priority": 1,
+                        "isActive": true
+                    },
+                    {
+                        "id": "well-formed-rule",
+                        "customerTags": ["wholesale"],
+                        "discountType": "percentage",
+                        "discountValue": 10.0,
+                        "priority": 1,
+                        "isActive": true
+                    }
+                ]
+            }
+        "#;
+
+        let result = run_function_with_input(
+            run,
+            &format!(
+                r#"
+                {{
+                    "discountNode": {{
+                        "metafield": {{
+                            "jsonValue": {config_json}
+                        }}
+                    }},
+                    "cart": {{
+                        "cost": {{
+                            "totalAmount": {{
+                                "amount": "0.00",
+                                "currencyCode": "USD"
+                            }}
+                        }},
+                        "buyerIdentity": {{
+                            "customer": {{
+                                "tags": ["wholesale"]
+                            }}
+                        }},
+                        "lines": [{}]
+                    }}
+                }}
+                "#,
+                cart_with_quantity(1)
+            ),
+        )?;
+
+        assert_eq!(result.discounts.len(), 1);
+        assert_eq!(
+            result.discounts[0].value,
+            schema::Value::Percentage(schema::Percentage { value: 10.0 })
+        );
+        Ok(())
+    }
+
+    fn run_with_cart(pricing_rules: Vec<PricingRule>, buyer_tags: &[&str], lines_json: &str) -> Result<schema::FunctionRunResult> {
+        let config = Configuration {
+            pricing_rules,
+            resolution: Resolution::PriorityFirst,
+        };
+        let buyer_tags_json = serde_json::to_string(buyer_tags).unwrap();
+
+        run_function_with_input(
+            run,
+            &format!(
+                r#"
+                {{
+                    "discountNode": {{
+                        "metafield": {{
+                            "jsonValue": {}
+                        }}
+                    }},
+                    "cart": {{
+                        "cost": {{
+                            "totalAmount": {{
+                                "amount": "0.00",
+                                "currencyCode": "USD"
+                            }}
+                        }},
+                        "buyerIdentity": {{
+                            "customer": {{
+                                "tags": {buyer_tags_json}
+                            }}
+                        }},
+                        "lines": [{lines_json}]
+                    }}
+                }}
+                "#,
+                serde_json::to_string(&config).unwrap()
+            ),
+        )
+    }
+
+    #[test]
+    fn test_all_tags_required() -> Result<()> {
+        let rule = PricingRule {
+            id: "all-tags-rule".to_string(),
+            customer_tags: vec!["wholesale".to_string(), "vip".to_string()],
+            match_mode: MatchMode::AllTags,
+            ..tiered_rule()
+        };
+
+        let partial = run_with_cart(vec![rule.clone()], &["wholesale"], &cart_with_quantity(1))?;
+        assert!(partial.discounts.is_empty());
+
+        let full = run_with_cart(vec![rule], &["wholesale", "vip"], &cart_with_quantity(1))?;
+        assert_eq!(full.discounts.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_excluded_tag_disqualifies_rule() -> Result<()> {
+        let rule = PricingRule {
+            id: "excluded-tag-rule".to_string(),
+            excluded_customer_tags: vec!["blocked".to_string()],
+            ..tiered_rule()
+        };
+
+        let result = run_with_cart(vec![rule], &["wholesale", "blocked"], &cart_with_quantity(1))?;
+
+        assert!(result.discounts.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_excluded_product_removed_from_matched_collection() -> Result<()> {
+        let rule = PricingRule {
+            id: "collection-rule".to_string(),
+            collection_ids: vec!["gid://shopify/Collection/1".to_string()],
+            excluded_product_ids: vec!["gid://shopify/Product/2".to_string()],
+            ..tiered_rule()
+        };
+
+        let lines_json = r#"
+            {
+                "id": "gid://shopify/CartLine/1",
+                "quantity": 1,
+                "cost": {
+                    "totalAmount": {
+                        "amount": "100.00",
+                        "currencyCode": "USD"
+                    }
+                },
+                "merchandise": {
+                    "__typename": "ProductVariant",
+                    "id": "gid://shopify/ProductVariant/1",
+                    "product": {
+                        "id": "gid://shopify/Product/1",
+                        "title": "Included Product",
+                        "inAnyCollection": [
+                            { "collectionId": "gid://shopify/Collection/1", "isMember": true }
+                        ]
+                    }
+                }
+            },
+            {
+                "id": "gid://shopify/CartLine/2",
+                "quantity": 1,
+                "cost": {
+                    "totalAmount": {
+                        "amount": "100.00",
+                        "currencyCode": "USD"
+                    }
+                },
+                "merchandise": {
+                    "__typename": "ProductVariant",
+                    "id": "gid://shopify/ProductVariant/2",
+                    "product": {
+                        "id": "gid://shopify/Product/2",
+                        "title": "Excluded Product",
+                        "inAnyCollection": [
+                            { "collectionId": "gid://shopify/Collection/1", "isMember": true }
+                        ]
+                    }
+                }
+            }
+        "#;
+
+        let result = run_with_cart(vec![rule], &["wholesale"], lines_json)?;
+
+        assert_eq!(result.discounts.len(), 1);
+        assert_eq!(
+            result.discounts[0].targets,
+            vec![schema::Target::CartLine(schema::CartLineTarget {
+                id: "gid://shopify/CartLine/1".to_string(),
+                quantity: None,
+            })]
+        );
+        Ok(())
+    }
+
+    fn run_best_for_customer(line_amount: &str) -> Result<schema::FunctionRunResult> {
+        let config_json = r#"
+            {
+                "resolution": "bestForCustomer",
+                "pricingRules": [
+                    {
+                        "id": "low-priority-percentage",
+                        "customerTags": ["wholesale"],
+                        "discountType": "percentage",
+                        "discountValue": 20.0,
+                        "priority": 1,
+                        "isActive": true
+                    },
+                    {
+                        "id": "high-priority-fixed",
+                        "customerTags": ["wholesale"],
+                        "discountType": "fixed",
+                        "discountValue": 0.0,
+                        "amountMinor": 500,
+                        "currencyCode": "USD",
+                        "priority": 2,
+                        "isActive": true
+                    }
+                ]
+            }
+        "#;
+
+        run_function_with_input(
+            run,
+            &format!(
+                r#"
+                {{
+                    "discountNode": {{
+                        "metafield": {{
+                            "jsonValue": {config_json}
+                        }}
+                    }},
+                    "cart": {{
+                        "cost": {{
+                            "totalAmount": {{
+                                "amount": "0.00",
+                                "currencyCode": "USD"
+                            }}
+                        }},
+                        "buyerIdentity": {{
+                            "customer": {{
+                                "tags": ["wholesale"]
+                            }}
+                        }},
+                        "lines": [
+                            {{
+                                "id": "gid://shopify/CartLine/1",
+                                "quantity": 1,
+                                "cost": {{
+                                    "totalAmount": {{
+                                        "amount": "{line_amount}",
+                                        "currencyCode": "USD"
+                                    }}
+                                }},
+                                "merchandise": {{
+                                    "__typename": "ProductVariant",
+                                    "id": "gid://shopify/ProductVariant/1",
+                                    "product": {{
+                                        "id": "gid://shopify/Product/1",
+                                        "title": "Test Product",
+                                        "inAnyCollection": []
+                                    }}
+                                }}
+                            }}
+                        ]
+                    }}
+                }}
+                "#
+            ),
+        )
+    }
+
+    #[test]
+    fn test_best_for_customer_prefers_lower_priority_percentage() -> Result<()> {
+        // $100 line: 20% off ($80 final) beats $5 off ($95 final), even
+        // though the fixed rule has higher priority.
+        let result = run_best_for_customer("100.00")?;
+
+        assert_eq!(
+            result.discounts[0].value,
+            schema::Value::Percentage(schema::Percentage { value: 20.0 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_best_for_customer_prefers_higher_priority_fixed() -> Result<()> {
+        // $10 line: $5 off ($5 final) beats 20% off ($8 final), even
+        // though the percentage rule has lower priority.
+        let result = run_best_for_customer("10.00")?;
+
+        assert_eq!(
+            result.discounts[0].value,
+            schema::Value::FixedAmount(schema::FixedAmount { amount: 5.0 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_best_for_customer_handles_over_100_percent_discount_without_panicking() -> Result<()> {
+        // An admin-entered percentage over 100 would otherwise rank as a
+        // negative final price; it must still resolve to a valid discount
+        // rather than panicking or producing nonsense ranking behavior.
+        let config_json = r#"
+            {
+                "resolution": "bestForCustomer",
+                "pricingRules": [
+                    {
+                        "id": "over-100-percent-rule",
+                        "customerTags": ["wholesale"],
+                        "discountType": "percentage",
+                        "discountValue": 150.0,
+                        "priority": 1,
+                        "isActive": true
+                    }
+                ]
+            }
+        "#;
+
+        let result = run_function_with_input(
+            run,
+            &format!(
+                r#"
+                {{
+                    "discountNode": {{
+                        "metafield": {{
+                            "jsonValue": {config_json}
+                        }}
+                    }},
+                    "cart": {{
+                        "cost": {{
+                            "totalAmount": {{
+                                "amount": "0.00",
+                                "currencyCode": "USD"
+                            }}
+                        }},
+                        "buyerIdentity": {{
+                            "customer": {{
+                                "tags": ["wholesale"]
+                            }}
+                        }},
+                        "lines": [{}]
+                    }}
+                }}
+                "#,
+                cart_with_quantity(1)
+            ),
+        )?;
+
+        assert_eq!(result.discounts.len(), 1);
+        assert_eq!(
+            result.discounts[0].value,
+            schema::Value::Percentage(schema::Percentage { value: 150.0 })
+        );
+        Ok(())
+    }
 }